@@ -1,10 +1,12 @@
 extern crate proc_macro;
+extern crate proc_macro2;
 extern crate syn;
 #[macro_use]
 extern crate quote;
 extern crate darling;
 
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 
 #[proc_macro_derive(ENum, attributes(e_num))]
 pub fn e_num_derive(input: TokenStream) -> TokenStream {
@@ -13,14 +15,23 @@ pub fn e_num_derive(input: TokenStream) -> TokenStream {
   impl_e_num(&ast)
 }
 
+#[derive(FromField, Debug, Clone)]
+#[darling(attributes(e_num), forward_attrs(allow, doc, cfg))]
+struct VariantField {
+  /// `Some` for named struct fields, `None` for tuple fields.
+  ident: Option<syn::Ident>,
+  ty: syn::Type,
+}
+
 #[derive(Debug)]
 enum VariantStyle {
-  /// A single tuple variant with type T where T: ENum
-  /// e.g.
+  /// A tuple or struct variant whose fields all implement `ENum`,
+  /// packed one after another above the tag bits, e.g.
   /// ```ignore
-  /// A(usize),
+  /// A(u32, u16),
+  /// A { x: u32, y: u16 },
   /// ```
-  Field(syn::Type),
+  Field(Vec<VariantField>, bool),
   /// A variant with nothing special about it
   /// e.g.
   /// ```ignore
@@ -39,6 +50,12 @@ struct Variant {
   style: VariantStyle,
   name: syn::Ident,
   constant_name: syn::Ident,
+  /// Set by `#[e_num(default)]`: this variant is returned by
+  /// `try_from_num` when no other variant's tag matches.
+  is_default: bool,
+  /// Extra tags set by `#[e_num(alternatives = [...])]` that also
+  /// decode to this variant, alongside its primary tag.
+  alternatives: Vec<usize>,
 }
 
 impl Variant {
@@ -49,16 +66,22 @@ impl Variant {
       let fields = var.fields;
       use darling::ast::Style;
       match fields.style {
-        Style::Tuple => {
-          if fields.fields.len() != 1 {
-            panic!("Invalid fields for");
-          }
-          VariantStyle::Field(fields.fields.first().unwrap().clone())
-        }
+        Style::Tuple => VariantStyle::Field(fields.fields, false),
+        Style::Struct => VariantStyle::Field(fields.fields, true),
         Style::Unit => VariantStyle::Unit,
-        Style::Struct => panic!("ENum can't have a struct variant"),
       }
     };
+    if var.default {
+      if let VariantStyle::Unit = style {
+      } else {
+        panic!("#[e_num(default)] can only be used on a unit variant");
+      }
+    }
+    if !var.alternatives.is_empty() {
+      if let VariantStyle::Field(..) = style {
+        panic!("#[e_num(alternatives)] can only be used on a unit or constant variant");
+      }
+    }
     let const_ident = syn::Ident::new(
       &format!("{}_MASK", var.ident.to_string().to_uppercase()),
       syn::export::Span::call_site(),
@@ -67,6 +90,8 @@ impl Variant {
       name: var.ident,
       constant_name: const_ident,
       style,
+      is_default: var.default,
+      alternatives: var.alternatives.into_iter().map(|n| n.0).collect(),
     }
   }
 }
@@ -84,16 +109,73 @@ fn round_up(num_to_round: usize) -> usize {
   v
 }
 
+/// Synthesize the tuple-field binding names `v0, v1, ...` used to
+/// destructure and repack a tuple variant's fields.
+fn tuple_idents(len: usize) -> Vec<syn::Ident> {
+  (0..len)
+    .map(|i| syn::Ident::new(&format!("v{}", i), syn::export::Span::call_site()))
+    .collect()
+}
+
+/// The identifier each field of a `Field` variant is bound to in a
+/// match pattern: the field's own name for struct variants, `v{i}`
+/// for tuple variants.
+fn field_binding_idents(fields: &[VariantField], is_struct: bool) -> Vec<syn::Ident> {
+  if is_struct {
+    fields.iter().map(|f| f.ident.clone().unwrap()).collect()
+  } else {
+    tuple_idents(fields.len())
+  }
+}
+
+/// The cumulative bit offset each field is packed at: the first field
+/// sits right above the tag bits, and each following field sits above
+/// however many bits the previous ones occupy.
+fn field_shifts(mask_size: usize, fields: &[VariantField]) -> Vec<TokenStream2> {
+  let mut shifts = Vec::with_capacity(fields.len());
+  let mut shift = quote! { #mask_size };
+  for field in fields {
+    shifts.push(shift.clone());
+    let ty = &field.ty;
+    shift = quote! { #shift + <#ty as ENum>::BITS as usize };
+  }
+  shifts
+}
+
 fn impl_e_num(ast: &syn::DeriveInput) -> TokenStream {
   let name = &ast.ident;
-  let Attr { start_at, data } = Attr::from_derive_input(ast)
+  let Attr {
+    start_at,
+    repr,
+    serde,
+    data,
+  } = Attr::from_derive_input(ast)
     .unwrap_or_else(|err| panic!("Error while parsing attributes: {}", err));
   let start_at = start_at.0;
+  let repr_ty = &repr.ty;
+  let repr_bits = repr.bits;
   let data = data
     .take_enum()
     .unwrap_or_else(|| panic!("Can't derive struct for ENum"));
-  let leading = (round_up(data.len() + start_at) - 1).leading_zeros() as usize;
-  let mask_size = 64 - leading;
+  // Number of bits needed to represent the tag, computed independently
+  // of `repr_bits`: it's intrinsic to the variant count, not the
+  // container width.
+  let unused_bits = (round_up(data.len() + start_at) - 1).leading_zeros() as usize;
+  let mask_size = 64 - unused_bits;
+  if mask_size > repr_bits {
+    panic!(
+      "{} needs {} bits to tag its {} variants, but #[e_num(repr = \"{}\")] only holds {} bits",
+      name,
+      mask_size,
+      data.len(),
+      quote! { #repr_ty },
+      repr_bits
+    );
+  }
+  // Unlike `mask_size`, `leading` is used to clear the high bits of a
+  // `#repr_ty`-wide number, so it has to be measured against that
+  // type's actual width.
+  let leading = repr_bits - mask_size;
   let vars = {
     let mut vec = data.into_iter().map(Variant::from_var).collect::<Vec<_>>();
     vec.sort_by(|var1, var2| {
@@ -107,30 +189,60 @@ fn impl_e_num(ast: &syn::DeriveInput) -> TokenStream {
     });
     vec
   };
+  let default_vars = vars.iter().filter(|var| var.is_default).collect::<Vec<_>>();
+  if default_vars.len() > 1 {
+    panic!("Only one variant can be marked #[e_num(default)]");
+  }
+  let fallback = match default_vars.first() {
+    Some(Variant { name: var_name, .. }) => quote! { Some(#name::#var_name) },
+    None => quote! { None },
+  };
   let const_names = vars.iter().map(|var| &var.constant_name);
   let const_vals = vars
     .iter()
     .enumerate()
     .map(|(i, Variant { style, .. })| match style {
       VariantStyle::Constant(expr) => quote! { #expr },
-      VariantStyle::Unit | VariantStyle::Field(_) => {
+      VariantStyle::Unit | VariantStyle::Field(..) => {
         let num = start_at + i;
         quote! { #num }
       }
     }).collect::<Vec<_>>();
+  // The counted tags are `usize` literals, so cast each one to the repr.
+  let typed_consts = const_names.zip(const_vals.iter()).map(|(const_name, const_val)| {
+    quote! { const #const_name: #repr_ty = (#const_val) as #repr_ty; }
+  });
   let const_defs = quote! {
-    #(const #const_names: usize = #const_vals;)*
+    #(#typed_consts)*
   };
+  // Constant tags and alternatives are exact matches: a number equal to
+  // one of them never decodes through another variant's low-bit check.
+  let exact_tags = vars
+    .iter()
+    .flat_map(|var| {
+      let own = match var.style {
+        VariantStyle::Constant(_) => Some(&var.constant_name),
+        VariantStyle::Field(..) | VariantStyle::Unit => None,
+      };
+      own.map(|c| quote! { #c })
+        .into_iter()
+        .chain(var.alternatives.iter().map(|alt| quote! { #alt }))
+    }).collect::<Vec<_>>();
   let checks = vars.iter().map(
     |Variant {
        constant_name,
        style,
+       alternatives,
        ..
-     }| match style {
-      VariantStyle::Constant(_) => quote! { num == #constant_name },
-      VariantStyle::Field(_) | VariantStyle::Unit => {
-        quote! { num << #leading >> #leading == #constant_name }
-      }
+     }| {
+      let exact_tags = exact_tags.iter();
+      let base = match style {
+        VariantStyle::Constant(_) => quote! { num == #constant_name },
+        VariantStyle::Field(..) | VariantStyle::Unit => {
+          quote! { num << #leading >> #leading == #constant_name #(&& num != #exact_tags)* }
+        }
+      };
+      quote! { (#base) #(|| num == #alternatives)* }
     },
   );
   let outputs = vars.iter().map(
@@ -140,8 +252,37 @@ fn impl_e_num(ast: &syn::DeriveInput) -> TokenStream {
        ..
      }| match style {
       VariantStyle::Constant(_) | VariantStyle::Unit => quote! { Some(#name::#var_name) },
-      VariantStyle::Field(ty) => {
-        quote! { <#ty as ENum>::try_from_num(num >> #mask_size).map(|val| #name::#var_name(val)) }
+      VariantStyle::Field(fields, is_struct) => {
+        let shifts = field_shifts(mask_size, fields);
+        let last = fields.len() - 1;
+        let binds = (0..fields.len())
+          .map(|i| syn::Ident::new(&format!("f{}", i), syn::export::Span::call_site()))
+          .collect::<Vec<_>>();
+        let decodes = fields.iter().zip(shifts.iter()).zip(binds.iter()).enumerate().map(
+          |(i, ((field, shift), bind))| {
+            let ty = &field.ty;
+            if i == last {
+              quote! {
+                let #bind = <#ty as ENum>::try_from_num((num >> (#shift)) as <#ty as ENum>::Repr)?;
+              }
+            } else {
+              quote! {
+                let #bind = <#ty as ENum>::try_from_num(
+                  ((num >> (#shift)) & (((1 as #repr_ty) << (<#ty as ENum>::BITS as usize)) - 1)) as <#ty as ENum>::Repr
+                )?;
+              }
+            }
+          },
+        );
+        let construct = if *is_struct {
+          let idents = fields.iter().map(|f| f.ident.clone().unwrap());
+          quote! { #name::#var_name { #(#idents: #binds),* } }
+        } else {
+          quote! { #name::#var_name(#(#binds),*) }
+        };
+        quote! {
+          (|| { #(#decodes)* Some(#construct) })()
+        }
       }
     },
   );
@@ -151,7 +292,14 @@ fn impl_e_num(ast: &syn::DeriveInput) -> TokenStream {
        style,
        ..
      }| match style {
-      VariantStyle::Field(_) => quote! { #name::#var_name(val) },
+      VariantStyle::Field(fields, is_struct) => {
+        let binds = field_binding_idents(fields, *is_struct);
+        if *is_struct {
+          quote! { #name::#var_name { #(#binds),* } }
+        } else {
+          quote! { #name::#var_name(#(#binds),*) }
+        }
+      }
       VariantStyle::Constant(_) | VariantStyle::Unit => quote! { #name::#var_name },
     },
   );
@@ -161,30 +309,189 @@ fn impl_e_num(ast: &syn::DeriveInput) -> TokenStream {
        style,
        ..
      }| match style {
-      VariantStyle::Field(ty) => {
-        quote! { <#ty as ENum>::to_num(val) << #mask_size | #constant_name }
+      VariantStyle::Field(fields, is_struct) => {
+        let binds = field_binding_idents(fields, *is_struct);
+        let shifts = field_shifts(mask_size, fields);
+        let tys = fields.iter().map(|f| &f.ty);
+        quote! {
+          #constant_name #(| (<#tys as ENum>::to_num(#binds) as #repr_ty) << (#shifts))*
+        }
       }
       VariantStyle::Constant(_) | VariantStyle::Unit => quote! { #constant_name },
     },
   );
+  let try_converts = vars.iter().map(
+    |Variant {
+       constant_name,
+       style,
+       ..
+     }| match style {
+      VariantStyle::Field(fields, is_struct) => {
+        let binds = field_binding_idents(fields, *is_struct);
+        let shifts = field_shifts(mask_size, fields);
+        let tys = fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+        let checked_binds = (0..fields.len())
+          .map(|i| syn::Ident::new(&format!("v{}", i), syn::export::Span::call_site()))
+          .collect::<Vec<_>>();
+        let checks = tys.iter().zip(shifts.iter()).zip(binds.iter()).zip(checked_binds.iter()).map(
+          |(((ty, shift), bind), checked)| {
+            // Cast up to the container's own repr before checking
+            // leading zeros, so the check is measured against the
+            // width we're actually shifting within, not the field
+            // type's own (possibly narrower or wider) width.
+            quote! {
+              let #checked = <#ty as ENum>::to_num(#bind) as #repr_ty;
+              if #checked.leading_zeros() < (#shift) as u32 {
+                return None;
+              }
+            }
+          },
+        );
+        quote! {
+          (|| {
+            #(#checks)*
+            Some(#constant_name #(| (#checked_binds << (#shifts)))*)
+          })()
+        }
+      }
+      VariantStyle::Constant(_) | VariantStyle::Unit => quote! { Some(#constant_name) },
+    },
+  );
+  let field_widths = vars.iter().map(|Variant { style, .. }| match style {
+    VariantStyle::Field(fields, _) => {
+      let tys = fields.iter().map(|f| &f.ty);
+      quote! { 0usize #(+ <#tys as ENum>::BITS as usize)* }
+    }
+    VariantStyle::Constant(_) | VariantStyle::Unit => quote! { 0usize },
+  }).collect::<Vec<_>>();
+  // Only a variant's non-last fields need to be guaranteed to fit
+  // alongside the tag: they each need a fixed slot so the next field's
+  // shift is unambiguous. The last field just gets "whatever room is
+  // left" and packs into however many bits remain above it; if its own
+  // value doesn't fit there, that's the data-dependent overflow
+  // `try_to_num` already exists to catch at runtime.
+  let field_widths_sans_last = vars.iter().map(|Variant { style, .. }| match style {
+    VariantStyle::Field(fields, _) if fields.len() > 1 => {
+      let tys = fields[..fields.len() - 1].iter().map(|f| &f.ty);
+      quote! { 0usize #(+ <#tys as ENum>::BITS as usize)* }
+    }
+    VariantStyle::Field(..) | VariantStyle::Constant(_) | VariantStyle::Unit => quote! { 0usize },
+  }).collect::<Vec<_>>();
+  let max_width_fn = syn::Ident::new(
+    &format!("__e_num_{}_max_width", name),
+    syn::export::Span::call_site(),
+  );
+  let max_width = field_widths.iter().fold(quote! { 0usize }, |acc, width| {
+    quote! { #max_width_fn(#acc, #width) }
+  });
+  let max_width_sans_last = field_widths_sans_last.iter().fold(quote! { 0usize }, |acc, width| {
+    quote! { #max_width_fn(#acc, #width) }
+  });
+  // Every field but a variant's last one needs a guaranteed, fixed slot
+  // above the tag bits so the next field's shift is unambiguous; if
+  // that doesn't fit inside the container's own repr, this fails to
+  // compile with a fixed-size-array mismatch rather than letting the
+  // generated code hit a shift-overflow further down. The last field's
+  // own overflow is left to `try_to_num`'s runtime check, since it's
+  // free to use whatever room is left and whether it fits depends on
+  // the runtime value, not just the type.
+  let width_check_name = syn::Ident::new(
+    &format!("__e_num_{}_width_check", name),
+    syn::export::Span::call_site(),
+  );
+  let fieldless_vars = vars
+    .iter()
+    .filter(|var| match var.style {
+      VariantStyle::Unit | VariantStyle::Constant(_) => true,
+      VariantStyle::Field(..) => false,
+    }).collect::<Vec<_>>();
+  let fieldless_nums = fieldless_vars.iter().map(|var| {
+    let constant_name = &var.constant_name;
+    quote! { #constant_name }
+  });
+  let fieldless_names = fieldless_vars.iter().map(|var| &var.name);
+  let variant_count = vars.len();
+  let iter_impl = quote! {
+    impl #name {
+      /// The total number of variants this enum declares.
+      pub const VARIANT_COUNT: usize = #variant_count;
+
+      /// Every representable encoding of this enum's fieldless
+      /// (`Unit`/`Constant`) variants. Variants carrying a field are
+      /// skipped, since their value space is unbounded.
+      pub fn nums() -> impl Iterator<Item = #repr_ty> {
+        #const_defs
+        vec![#(#fieldless_nums),*].into_iter()
+      }
+
+      /// Every fieldless variant of this enum, reconstructed via
+      /// `from_num`.
+      pub fn variants() -> impl Iterator<Item = Self> {
+        Self::nums().map(Self::from_num)
+      }
+    }
+  };
+  let serde_impl = if serde {
+    quote! {
+      #[cfg(feature = "serde")]
+      impl ::serde::Serialize for #name {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+          S: ::serde::Serializer,
+        {
+          ::serde::Serialize::serialize(&self.to_num(), serializer)
+        }
+      }
+
+      #[cfg(feature = "serde")]
+      impl<'de> ::serde::Deserialize<'de> for #name {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+          D: ::serde::Deserializer<'de>,
+        {
+          let num = <#repr_ty as ::serde::Deserialize>::deserialize(deserializer)?;
+          Self::try_from_num(num).ok_or_else(|| {
+            ::serde::de::Error::custom(concat!(
+              "invalid numeric tag for ",
+              stringify!(#name)
+            ))
+          })
+        }
+      }
+    }
+  } else {
+    quote! {}
+  };
   let gen = quote! {
+    #[doc(hidden)]
+    const fn #max_width_fn(a: usize, b: usize) -> usize {
+      if a > b { a } else { b }
+    }
+
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    const #width_check_name: [(); (#mask_size + #max_width_sans_last <= #repr_bits) as usize] = [(); 1];
+
     impl ENum for #name {
-      fn try_from_num(num: usize) -> Option<Self> {
+      type Repr = #repr_ty;
+      const BITS: u32 = (#mask_size + #max_width) as u32;
+
+      fn try_from_num(num: #repr_ty) -> Option<Self> {
         #const_defs
         #(if #checks {
           #outputs
         } else)* {
-          None
+          #fallback
         }
       }
-      fn from_num(num: usize) -> Self {
+      fn from_num(num: #repr_ty) -> Self {
         if let Some(val) = Self::try_from_num(num) {
           val
         } else {
           panic!(concat!("Failure to parse number into ", stringify!(#name)));
         }
       }
-      fn to_num(&self) -> usize {
+      fn to_num(&self) -> #repr_ty {
         #const_defs
         match self {
           #(#matches => {
@@ -192,18 +499,30 @@ fn impl_e_num(ast: &syn::DeriveInput) -> TokenStream {
           }),*
         }
       }
+      fn try_to_num(&self) -> Option<#repr_ty> {
+        #const_defs
+        match self {
+          #(#matches => {
+            #try_converts
+          }),*
+        }
+      }
     }
 
-    impl From<usize> for #name {
-      fn from(num: usize) -> Self {
+    impl From<#repr_ty> for #name {
+      fn from(num: #repr_ty) -> Self {
         Self::from_num(num)
       }
     }
+
+    #iter_impl
+
+    #serde_impl
   };
   gen.into()
 }
 
-use darling::{FromDeriveInput, FromMeta, FromVariant};
+use darling::{FromDeriveInput, FromField, FromMeta, FromVariant};
 
 #[derive(Default, Debug)]
 struct AttrNum(usize);
@@ -229,13 +548,53 @@ impl FromMeta for AttrExpr {
   }
 }
 
+/// The backing integer type for the derived number, named by
+/// `#[e_num(repr = "...")]`. Defaults to `usize` when unspecified.
+#[derive(Debug)]
+struct ReprAttr {
+  ty: syn::Type,
+  bits: usize,
+}
+
+impl Default for ReprAttr {
+  fn default() -> Self {
+    ReprAttr {
+      ty: syn::parse_str("usize").unwrap(),
+      bits: 64,
+    }
+  }
+}
+
+impl FromMeta for ReprAttr {
+  fn from_string(value: &str) -> darling::Result<Self> {
+    let bits = match value {
+      "u8" => 8,
+      "u16" => 16,
+      "u32" => 32,
+      "u64" => 64,
+      "u128" => 128,
+      "usize" => 64,
+      other => panic!(
+        "Unsupported e_num repr `{}`; expected one of u8, u16, u32, u64, u128, usize",
+        other
+      ),
+    };
+    let ty = syn::parse_str(value).unwrap_or_else(|_| panic!("Invalid repr type `{}`", value));
+    Ok(ReprAttr { ty, bits })
+  }
+}
+
 #[derive(FromVariant, Debug)]
 #[darling(attributes(e_num), forward_attrs(allow, doc, cfg))]
 struct Var {
   #[darling(default)]
   constant: Option<AttrExpr>,
+  #[darling(default)]
+  default: bool,
+  #[darling(default)]
+  alternatives: Vec<AttrNum>,
   ident: syn::Ident,
-  fields: darling::ast::Fields<syn::Type>,
+  fields: darling::ast::Fields<VariantField>,
 }
 
 #[derive(FromDeriveInput)]
@@ -243,5 +602,12 @@ struct Var {
 struct Attr {
   #[darling(default)]
   pub start_at: AttrNum,
+  #[darling(default)]
+  pub repr: ReprAttr,
+  /// `#[e_num(serde)]`: also derive `Serialize`/`Deserialize` (behind
+  /// a `serde` feature of the crate that invokes the derive), routed
+  /// through `to_num`/`try_from_num`.
+  #[darling(default)]
+  pub serde: bool,
   pub data: darling::ast::Data<Var, ()>,
 }
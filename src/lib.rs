@@ -9,6 +9,8 @@
 //! enough so that the tagging can fit on the right of the number. If
 //! you're dealing with very large numbers in the fields or have a ton
 //! of variants, data on the left side of the value will likely be lost.
+//! Use `try_to_num()` instead of `to_num()` if you'd rather detect this
+//! up front than silently lose data.
 //!
 //! ## Basic Usage
 //!
@@ -74,6 +76,121 @@
 //!   assert_eq!(A::E.to_num(), 11);
 //! }
 //! ```
+//!
+//! ## Configurable backing type
+//!
+//! ```
+//! #[macro_use]
+//! extern crate e_num;
+//!
+//! use e_num::ENum;
+//!
+//! #[derive(ENum)]
+//! // pack into a u128 instead of the default usize, for enums with
+//! // fields too wide to fit in a usize's bits
+//! #[e_num(repr = "u128")]
+//! enum A {
+//!   B,
+//!   C(u128),
+//! }
+//!
+//! fn main() {
+//!   let num: u128 = A::C(85).to_num();
+//!   assert!(match A::from_num(num) {
+//!     A::C(inner) => inner == 85,
+//!     _ => false,
+//!   });
+//! }
+//! ```
+//!
+//! ## `default` and `alternatives`
+//!
+//! ```
+//! #[macro_use]
+//! extern crate e_num;
+//!
+//! use e_num::ENum;
+//!
+//! #[derive(ENum)]
+//! enum A {
+//!   // 3 and 11 are old wire codes for a variant that was later
+//!   // renumbered to 2
+//!   #[e_num(constant = 2)]
+//!   #[e_num(alternatives = [3, 11])]
+//!   B,
+//!   // any number that doesn't match another variant decodes to this
+//!   // one instead of failing
+//!   #[e_num(default)]
+//!   Unknown,
+//! }
+//!
+//! fn main() {
+//!   assert!(match A::from_num(11) {
+//!     A::B => true,
+//!     _ => false,
+//!   });
+//!   assert!(match A::from_num(999) {
+//!     A::Unknown => true,
+//!     _ => false,
+//!   });
+//! }
+//! ```
+//!
+//! ## `serde` integration
+//!
+//! Opt an enum in with `#[e_num(serde)]` to (de)serialize it as its
+//! plain numeric tag instead of the usual variant representation. The
+//! generated impls are behind `#[cfg(feature = "serde")]`, which is
+//! checked in the crate that derives the enum: that crate needs its own
+//! `serde` feature enabled and `serde` as a dependency.
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate e_num;
+//! extern crate serde;
+//! extern crate serde_json;
+//!
+//! use e_num::ENum;
+//!
+//! #[derive(ENum)]
+//! #[e_num(serde)]
+//! enum A {
+//!   B,
+//!   C(u64),
+//! }
+//!
+//! fn main() {
+//!   let json = serde_json::to_string(&A::C(85)).unwrap();
+//!   let a: A = serde_json::from_str(&json).unwrap();
+//!   assert!(match a {
+//!     A::C(inner) => inner == 85,
+//!     _ => false,
+//!   });
+//! }
+//! ```
+//!
+//! ## Counting and iterating variants
+//!
+//! ```
+//! #[macro_use]
+//! extern crate e_num;
+//!
+//! use e_num::ENum;
+//!
+//! #[derive(ENum)]
+//! enum A {
+//!   B,
+//!   C,
+//!   // fields have an unbounded value space, so D is skipped by
+//!   // `nums()`/`variants()`
+//!   D(u64),
+//! }
+//!
+//! fn main() {
+//!   assert_eq!(A::VARIANT_COUNT, 3);
+//!   assert_eq!(A::nums().collect::<Vec<_>>(), vec![A::B.to_num(), A::C.to_num()]);
+//! }
+//! ```
 
 #[allow(unused_imports)]
 #[doc(hidden)]
@@ -120,9 +237,120 @@ mod tests {
   fn constant_variant() {
     assert!(Test2::A.to_num() == 9);
   }
+
+  #[test]
+  fn try_to_num_overflow() {
+    assert!(Test1::B(5).try_to_num().is_some());
+    assert!(Test1::B(usize::max_value()).try_to_num().is_none());
+  }
+
+  #[derive(ENum)]
+  enum Test3 {
+    A,
+    B(u32, u16),
+    C { x: u16, y: u16 },
+  }
+
+  #[test]
+  fn multi_field_tuple_variant() {
+    let num = Test3::B(12, 34).to_num();
+    assert!(match Test3::from_num(num) {
+      Test3::B(x, y) => x == 12 && y == 34,
+      _ => false,
+    });
+  }
+
+  #[test]
+  fn multi_field_struct_variant() {
+    let num = Test3::C { x: 12, y: 34 }.to_num();
+    assert!(match Test3::from_num(num) {
+      Test3::C { x, y } => x == 12 && y == 34,
+      _ => false,
+    });
+  }
+
+  #[test]
+  fn variant_count_and_nums() {
+    assert_eq!(Test3::VARIANT_COUNT, 3);
+    assert_eq!(Test3::nums().collect::<Vec<_>>(), vec![Test3::A.to_num()]);
+    assert!(match Test3::variants().next() {
+      Some(Test3::A) => true,
+      _ => false,
+    });
+  }
+
+  #[derive(ENum)]
+  #[e_num(repr = "u128")]
+  enum Test4 {
+    A,
+    B(u128),
+  }
+
+  #[test]
+  fn custom_repr() {
+    let num: u128 = Test4::B(85).to_num();
+    assert!(match Test4::from_num(num) {
+      Test4::B(v) => v == 85,
+      _ => false,
+    });
+  }
+
+  #[derive(ENum)]
+  enum Test5 {
+    #[e_num(constant = 2)]
+    #[e_num(alternatives = [3, 11])]
+    A,
+    #[e_num(default)]
+    Unknown,
+  }
+
+  #[test]
+  fn alternatives() {
+    assert!(match Test5::from_num(2) {
+      Test5::A => true,
+      _ => false,
+    });
+    assert!(match Test5::from_num(11) {
+      Test5::A => true,
+      _ => false,
+    });
+  }
+
+  #[test]
+  fn default_fallback() {
+    assert!(match Test5::from_num(999) {
+      Test5::Unknown => true,
+      _ => false,
+    });
+  }
+
+  #[derive(ENum)]
+  #[e_num(serde)]
+  enum Test6 {
+    A,
+    B(u64),
+  }
+
+  #[test]
+  fn serde_attr_does_not_disturb_plain_encoding() {
+    let num = Test6::B(7).to_num();
+    assert!(match Test6::from_num(num) {
+      Test6::B(v) => v == 7,
+      _ => false,
+    });
+  }
 }
 
 pub trait ENum: Sized {
+  /// The integer type this type's numeric encoding is carried in.
+  ///
+  /// For a `#[derive(ENum)]`'d enum this defaults to `usize`, but can
+  /// be widened or narrowed with a container-level
+  /// `#[e_num(repr = "...")]` attribute.
+  type Repr;
+  /// The number of bits this type occupies when packed as a field
+  /// inside a derived enum's number representation.
+  const BITS: u32;
   /// Parse a number into the type.
   ///
   /// If you're `impl`ing `ENum` yourself, you don't *need* to
@@ -152,7 +380,7 @@ pub trait ENum: Sized {
   /// This function should panic if it cannot parse the number into
   /// its type; e.g. you should only pass to this function the output
   /// of `.to_num()`. If you want to handle a parsing error, use `try_from_num()`.
-  fn from_num(num: usize) -> Self {
+  fn from_num(num: Self::Repr) -> Self {
     Self::try_from_num(num).expect("Couldn't parse number into type")
   }
   /// The error-handling counterpart of `from_num()`.
@@ -179,7 +407,7 @@ pub trait ENum: Sized {
   ///   // handle error
   /// }
   /// ```
-  fn try_from_num(num: usize) -> Option<Self>;
+  fn try_from_num(num: Self::Repr) -> Option<Self>;
   /// Convert self to a serializable number.
   ///
   /// # Examples
@@ -200,23 +428,64 @@ pub trait ENum: Sized {
   ///   _ => false,
   /// });
   /// ```
-  fn to_num(&self) -> usize;
+  fn to_num(&self) -> Self::Repr;
+  /// The overflow-checked counterpart of `to_num()`.
+  ///
+  /// `to_num()` packs a field's value in by left-shifting it past the
+  /// tag bits, which silently throws away any set bits that don't fit.
+  /// This returns `None` instead of losing data when that shift would
+  /// overflow.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # #[macro_use] extern crate e_num;
+  /// # use e_num::ENum;
+  /// # #[derive(ENum)]
+  /// # enum A {
+  /// #   B,
+  /// #   C(u16),
+  /// # }
+  /// assert!(A::C(5).try_to_num().is_some());
+  /// ```
+  fn try_to_num(&self) -> Option<Self::Repr> {
+    Some(self.to_num())
+  }
 }
 
 macro_rules! impl_e_num_num {
   ($($num:ty),*) => {
     $(impl ENum for $num {
-      fn try_from_num(num:usize) -> Option<Self> {
-        Some(Self::from_num(num))
+      // A primitive's own type is always wide enough to carry its own
+      // value; routing it through a fixed-width `Repr` like `usize`
+      // would silently truncate types wider than that (e.g. `u128`).
+      type Repr = Self;
+      const BITS: u32 = (::std::mem::size_of::<$num>() * 8) as u32;
+      fn try_from_num(num: Self) -> Option<Self> {
+        Some(num)
       }
-      fn from_num(num: usize) -> Self {
-        num as Self
+      fn from_num(num: Self) -> Self {
+        num
       }
-      fn to_num(&self) -> usize {
-        *self as usize
+      fn to_num(&self) -> Self {
+        *self
       }
     })*
   };
 }
 
-impl_e_num_num!(usize, u64, u32, u16);
+impl_e_num_num!(usize, u64, u32, u16, u8, u128);
+
+impl ENum for bool {
+  type Repr = usize;
+  const BITS: u32 = 1;
+  fn try_from_num(num: usize) -> Option<Self> {
+    Some(num != 0)
+  }
+  fn from_num(num: usize) -> Self {
+    num != 0
+  }
+  fn to_num(&self) -> usize {
+    *self as usize
+  }
+}